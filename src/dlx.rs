@@ -0,0 +1,276 @@
+//! Exact-cover solver backend using Knuth's Dancing Links (Algorithm X).
+//!
+//! The puzzle is modelled as a 0/1 matrix whose columns are the board's
+//! cell constraints plus one column per piece (each piece must be used
+//! exactly once); each `(pid, placement)` pair becomes a row with 1s in
+//! the columns of the cells it covers and in that piece's column.
+
+use crate::puzzle::{Placement, Puzzle};
+
+/// Circular doubly-linked node arena. Index 0 is the root; indices
+/// `1..=num_cols` are the column headers; everything after that is a
+/// placement cell.
+struct Dlx {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    column: Vec<usize>,
+    size: Vec<usize>,
+    row: Vec<Option<usize>>,
+}
+
+const ROOT: usize = 0;
+
+impl Dlx {
+    fn new(num_cols: usize) -> Dlx {
+        let headers = num_cols + 1;
+        let mut dlx = Dlx {
+            left: (0..headers).collect(),
+            right: (0..headers).collect(),
+            up: (0..headers).collect(),
+            down: (0..headers).collect(),
+            column: (0..headers).collect(),
+            size: vec![0; headers],
+            row: vec![None; headers],
+        };
+        for i in 0..headers {
+            dlx.left[i] = (i + headers - 1) % headers;
+            dlx.right[i] = (i + 1) % headers;
+        }
+        dlx
+    }
+
+    fn add_node(&mut self, col: usize) -> usize {
+        let idx = self.left.len();
+        let up = self.up[col];
+        self.left.push(idx);
+        self.right.push(idx);
+        self.up.push(up);
+        self.down.push(col);
+        self.column.push(col);
+        self.row.push(None);
+        self.down[up] = idx;
+        self.up[col] = idx;
+        self.size[col] += 1;
+        idx
+    }
+
+    fn add_row(&mut self, row_id: usize, cols: &[usize]) {
+        let mut first = None;
+        let mut prev = None;
+        for &col in cols {
+            let idx = self.add_node(col);
+            self.row[idx] = Some(row_id);
+            if let Some(p) = prev {
+                self.right[p] = idx;
+                self.left[idx] = p;
+            } else {
+                first = Some(idx);
+            }
+            prev = Some(idx);
+        }
+        if let (Some(first), Some(last)) = (first, prev) {
+            self.right[last] = first;
+            self.left[first] = last;
+        }
+    }
+
+    fn cover(&mut self, col: usize) {
+        self.right[self.left[col]] = self.right[col];
+        self.left[self.right[col]] = self.left[col];
+
+        let mut i = self.down[col];
+        while i != col {
+            let mut j = self.right[i];
+            while j != i {
+                self.down[self.up[j]] = self.down[j];
+                self.up[self.down[j]] = self.up[j];
+                self.size[self.column[j]] -= 1;
+                j = self.right[j];
+            }
+            i = self.down[i];
+        }
+    }
+
+    fn uncover(&mut self, col: usize) {
+        let mut i = self.up[col];
+        while i != col {
+            let mut j = self.left[i];
+            while j != i {
+                self.size[self.column[j]] += 1;
+                self.down[self.up[j]] = j;
+                self.up[self.down[j]] = j;
+                j = self.left[j];
+            }
+            i = self.up[i];
+        }
+
+        self.right[self.left[col]] = col;
+        self.left[self.right[col]] = col;
+    }
+
+    /// S-heuristic: the live column with the fewest remaining rows.
+    fn choose_column(&self) -> Option<usize> {
+        let mut col = self.right[ROOT];
+        if col == ROOT {
+            return None;
+        }
+        let mut best = col;
+        while col != ROOT {
+            if self.size[col] < self.size[best] {
+                best = col;
+            }
+            col = self.right[col];
+        }
+        Some(best)
+    }
+
+    fn search(&mut self, partial: &mut Vec<usize>, solutions: &mut Vec<Vec<usize>>, explored: &mut usize) {
+        *explored += 1;
+
+        let col = match self.choose_column() {
+            None => {
+                solutions.push(partial.clone());
+                return;
+            }
+            Some(col) => col,
+        };
+        if self.size[col] == 0 {
+            return;
+        }
+
+        self.cover(col);
+        let mut r = self.down[col];
+        while r != col {
+            partial.push(self.row[r].unwrap());
+
+            let mut j = self.right[r];
+            while j != r {
+                self.cover(self.column[j]);
+                j = self.right[j];
+            }
+
+            self.search(partial, solutions, explored);
+
+            let mut j = self.left[r];
+            while j != r {
+                self.uncover(self.column[j]);
+                j = self.left[j];
+            }
+            partial.pop();
+
+            r = self.down[r];
+        }
+        self.uncover(col);
+    }
+}
+
+/// Solve `puzzle` as an exact-cover problem. Returns every complete
+/// solution, each as the `(piece id, placement)` pairs used, along with
+/// the number of search nodes explored.
+pub fn solve(puzzle: &Puzzle) -> (Vec<Vec<(usize, Placement)>>, usize) {
+    let num_cells = puzzle.num_cells();
+    let num_pieces = puzzle.pieces.len();
+    let num_cols = num_cells + num_pieces;
+
+    let mut dlx = Dlx::new(num_cols);
+    let mut rows: Vec<(usize, Placement)> = vec![];
+
+    for (pid, piece) in puzzle.pieces.iter().enumerate() {
+        for placement in piece.placements() {
+            let mut cols: Vec<usize> = (0..num_cells)
+                .filter(|&cell| placement.get(cell))
+                .map(|cell| cell + 1)
+                .collect();
+            cols.push(num_cells + pid + 1);
+
+            dlx.add_row(rows.len(), &cols);
+            rows.push((pid, placement.clone()));
+        }
+    }
+
+    let mut partial = vec![];
+    let mut row_solutions = vec![];
+    let mut explored = 0;
+    dlx.search(&mut partial, &mut row_solutions, &mut explored);
+
+    let solutions = row_solutions
+        .into_iter()
+        .map(|row_ids| row_ids.into_iter().map(|r| rows[r].clone()).collect())
+        .collect();
+
+    (solutions, explored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Knuth's textbook exact-cover example (TAOCP 4B, 7.2.2.1): 7 columns,
+    /// rows as below, with the unique exact cover being rows 1, 3 and 5.
+    fn example_matrix() -> Dlx {
+        let mut dlx = Dlx::new(7);
+        dlx.add_row(0, &[1, 4, 7]);
+        dlx.add_row(1, &[1, 4]);
+        dlx.add_row(2, &[4, 5, 7]);
+        dlx.add_row(3, &[3, 5, 6]);
+        dlx.add_row(4, &[2, 3, 6, 7]);
+        dlx.add_row(5, &[2, 7]);
+        dlx
+    }
+
+    #[test]
+    fn search_finds_the_unique_exact_cover() {
+        let mut dlx = example_matrix();
+        let mut partial = vec![];
+        let mut solutions = vec![];
+        let mut explored = 0;
+        dlx.search(&mut partial, &mut solutions, &mut explored);
+
+        assert_eq!(solutions.len(), 1);
+        let mut solution = solutions[0].clone();
+        solution.sort();
+        assert_eq!(solution, vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn cover_removes_column_and_its_rows_from_the_header_list() {
+        let mut dlx = example_matrix();
+        let before: Vec<usize> = std::iter::successors(Some(dlx.right[ROOT]), |&c| {
+            (c != ROOT).then(|| dlx.right[c])
+        })
+        .collect();
+        assert!(before.contains(&4));
+        assert_eq!(dlx.size[4], 3);
+
+        dlx.cover(4);
+
+        let after: Vec<usize> = std::iter::successors(Some(dlx.right[ROOT]), |&c| {
+            (c != ROOT).then(|| dlx.right[c])
+        })
+        .collect();
+        assert!(!after.contains(&4));
+        // Rows 0 and 2 also touch column 7, which should have shrunk too.
+        assert_eq!(dlx.size[7], 2);
+    }
+
+    #[test]
+    fn uncover_restores_exactly_what_cover_removed() {
+        let mut dlx = example_matrix();
+        let before_right = dlx.right.clone();
+        let before_left = dlx.left.clone();
+        let before_up = dlx.up.clone();
+        let before_down = dlx.down.clone();
+        let before_size = dlx.size.clone();
+
+        dlx.cover(4);
+        dlx.uncover(4);
+
+        assert_eq!(dlx.right, before_right);
+        assert_eq!(dlx.left, before_left);
+        assert_eq!(dlx.up, before_up);
+        assert_eq!(dlx.down, before_down);
+        assert_eq!(dlx.size, before_size);
+    }
+}
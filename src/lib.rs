@@ -0,0 +1,3 @@
+pub mod dlx;
+pub mod puzzle;
+pub mod solver;
@@ -12,6 +12,11 @@ struct Args {
     /// Returns solution to sudoku
     #[arg(short, long)]
     verbose: bool,
+
+    /// Solve as an exact cover problem using Dancing Links instead of the
+    /// bespoke backtracker
+    #[arg(long)]
+    dlx: bool,
 }
 
 fn main() -> io::Result<()> {
@@ -20,7 +25,11 @@ fn main() -> io::Result<()> {
     let puzzle = Puzzle::from_csv(args.puzzle)?;
 
     let mut solver = Solver::build(args.verbose);
-    solver.begin(&puzzle);
+    if args.dlx {
+        solver.begin_dlx(&puzzle);
+    } else {
+        solver.begin(&puzzle);
+    }
 
     // if args.verbose {
     let duration = solver.start_time.unwrap().elapsed();
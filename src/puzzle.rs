@@ -5,47 +5,155 @@ use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
 use std::{fmt, io};
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Bitset(pub u64);
+/// Bits per backing word; boards needing more than this many cells simply
+/// spill into additional words.
+const WORD_BITS: usize = u64::BITS as usize;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Bitset(pub Vec<u64>);
 
 pub type Board = Bitset;
 pub type Placement = Bitset;
 
 impl Bitset {
-    pub fn empty() -> Bitset {
-        Bitset(0)
+    /// Number of `u64` words needed to hold `cells` bits.
+    pub fn words_for(cells: usize) -> usize {
+        (cells + WORD_BITS - 1) / WORD_BITS
+    }
+
+    pub fn empty(words: usize) -> Bitset {
+        Bitset(vec![0; words])
     }
 
     pub fn from_orientation(orientation: &Orientation, dim: &Coord) -> Bitset {
-        let mut mask = Bitset(0);
+        let cells = dim.cells();
+        let mut mask = Bitset::empty(Self::words_for(cells));
         for coord in &orientation.0 {
-            mask.0 |= 1 << coord.z * dim.y * dim.x + coord.y * dim.x + coord.x
+            let index = (coord.z * dim.y * dim.x + coord.y * dim.x + coord.x) as usize;
+            mask.set(index);
         }
         mask
     }
 
     pub fn get(&self, index: usize) -> bool {
-        (self.0 >> index) & 1 == 1
+        (self.0[index / WORD_BITS] >> (index % WORD_BITS)) & 1 == 1
     }
 
     pub fn set(&mut self, index: usize) {
-        self.0 |= 1 << index;
+        self.0[index / WORD_BITS] |= 1 << (index % WORD_BITS);
+    }
+
+    pub fn intersects(&self, other: &Bitset) -> bool {
+        self.0.iter().zip(&other.0).any(|(a, b)| a & b != 0)
+    }
+
+    pub fn xor(&self, other: &Bitset) -> Bitset {
+        Bitset(self.0.iter().zip(&other.0).map(|(a, b)| a ^ b).collect())
+    }
+
+    pub fn union(&self, other: &Bitset) -> Bitset {
+        Bitset(self.0.iter().zip(&other.0).map(|(a, b)| a | b).collect())
+    }
+
+    pub fn intersection(&self, other: &Bitset) -> Bitset {
+        Bitset(self.0.iter().zip(&other.0).map(|(a, b)| a & b).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|word| *word == 0)
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
     }
 
-    pub fn intersects(&self, other: Bitset) -> bool {
-        (self.0 & other.0) != 0
+    pub fn first_set(&self) -> Option<usize> {
+        self.0.iter().enumerate().find_map(|(i, word)| {
+            (*word != 0).then(|| i * WORD_BITS + word.trailing_zeros() as usize)
+        })
     }
 
-    pub fn xor(&self, other: Bitset) -> Bitset {
-        Bitset(self.0 ^ other.0)
+    /// Shift every bit towards higher indices, discarding anything that
+    /// falls off the top.
+    pub fn shl(&self, shift: usize) -> Bitset {
+        let word_shift = shift / WORD_BITS;
+        let bit_shift = shift % WORD_BITS;
+        let len = self.0.len();
+        let mut out = vec![0u64; len];
+        for i in (word_shift..len).rev() {
+            let src = i - word_shift;
+            out[i] |= if bit_shift == 0 {
+                self.0[src]
+            } else {
+                self.0[src] << bit_shift
+            };
+            if bit_shift != 0 && src > 0 {
+                out[i] |= self.0[src - 1] >> (WORD_BITS - bit_shift);
+            }
+        }
+        Bitset(out)
+    }
+
+    /// Shift every bit towards lower indices, discarding anything that
+    /// falls off the bottom.
+    pub fn shr(&self, shift: usize) -> Bitset {
+        let word_shift = shift / WORD_BITS;
+        let bit_shift = shift % WORD_BITS;
+        let len = self.0.len();
+        let mut out = vec![0u64; len];
+        for i in 0..len.saturating_sub(word_shift) {
+            let src = i + word_shift;
+            out[i] |= if bit_shift == 0 {
+                self.0[src]
+            } else {
+                self.0[src] >> bit_shift
+            };
+            if bit_shift != 0 && src + 1 < len {
+                out[i] |= self.0[src + 1] << (WORD_BITS - bit_shift);
+            }
+        }
+        Bitset(out)
     }
+}
 
-    pub fn union(&self, other: Bitset) -> Bitset {
-        Bitset(self.0 | other.0)
+/// Face-adjacency masks for a board of a given shape, precomputed once so
+/// that flood-filling the free cells doesn't wrap around at the edges of
+/// the board.
+pub struct NeighbourMasks {
+    pos_x: Bitset,
+    neg_x: Bitset,
+    pos_y: Bitset,
+    neg_y: Bitset,
+    pos_z: Bitset,
+    neg_z: Bitset,
+    y_stride: usize,
+    z_stride: usize,
+}
+
+impl NeighbourMasks {
+    fn build(dim: &Coord) -> NeighbourMasks {
+        NeighbourMasks {
+            pos_x: Puzzle::mask_where(dim, |x, _, _| x < dim.x - 1),
+            neg_x: Puzzle::mask_where(dim, |x, _, _| x > 0),
+            pos_y: Puzzle::mask_where(dim, |_, y, _| y < dim.y - 1),
+            neg_y: Puzzle::mask_where(dim, |_, y, _| y > 0),
+            pos_z: Puzzle::mask_where(dim, |_, _, z| z < dim.z - 1),
+            neg_z: Puzzle::mask_where(dim, |_, _, z| z > 0),
+            y_stride: dim.x as usize,
+            z_stride: (dim.x * dim.y) as usize,
+        }
     }
 
-    pub fn intersection(&self, other: Bitset) -> Bitset {
-        Bitset(self.0 & other.0)
+    /// All cells face-adjacent to some cell in `region`.
+    pub fn expand(&self, region: &Bitset) -> Bitset {
+        region
+            .intersection(&self.pos_x)
+            .shl(1)
+            .union(&region.intersection(&self.neg_x).shr(1))
+            .union(&region.intersection(&self.pos_y).shl(self.y_stride))
+            .union(&region.intersection(&self.neg_y).shr(self.y_stride))
+            .union(&region.intersection(&self.pos_z).shl(self.z_stride))
+            .union(&region.intersection(&self.neg_z).shr(self.z_stride))
     }
 }
 
@@ -54,6 +162,7 @@ pub struct Piece {
     pub name: String,
     pub id: String,
     pub base: Orientation,
+    pub allow_reflection: bool,
     pub placements: Vec<Placement>,
 }
 
@@ -72,11 +181,12 @@ impl fmt::Debug for Piece {
     }
 }
 impl Piece {
-    fn new(name: String, id: String, base: Orientation) -> Piece {
+    fn new(name: String, id: String, base: Orientation, allow_reflection: bool) -> Piece {
         Piece {
             name,
             id,
             base,
+            allow_reflection,
             placements: vec![],
         }
     }
@@ -90,7 +200,19 @@ impl Piece {
         // Each face can be in four rotations
         // Good resource: https://www.euclideanspace.com/maths/geometry/rotations/euler/examples/index.htm
         //      Matrix rep: https://www.euclideanspace.com/maths/algebra/matrix/transforms/examples/index.htm
-        let mut current_orientation = self.base.clone();
+        let mut orientations = Self::rotations(&self.base);
+        if self.allow_reflection {
+            // Chiral pieces may also be placed as their mirror image, so
+            // compose every rotation with a single reflection as well.
+            orientations.extend(Self::rotations(&self.base.reflect()));
+        }
+        let unique_orientations: Vec<Orientation> =
+            orientations.iter().unique().map(|x| x.clone()).collect();
+        unique_orientations
+    }
+
+    fn rotations(base: &Orientation) -> Vec<Orientation> {
+        let mut current_orientation = base.clone();
         let mut orientations: Vec<Orientation> = vec![];
         for _ in 0..4 {
             orientations.push(current_orientation.clone());
@@ -112,31 +234,33 @@ impl Piece {
 
             current_orientation.rotate(1, 0, 0);
         }
-        let unique_orientations: Vec<Orientation> =
-            orientations.iter().unique().map(|x| x.clone()).collect();
-        unique_orientations
+        orientations
     }
 }
 
 #[derive(Clone, Eq, Debug)]
 pub struct Orientation(Vec<Coord>);
 
+impl Orientation {
+    /// Cell coordinates, normalised and sorted so that two orientations
+    /// occupying the same set of cells compare/hash equal regardless of
+    /// board size or the order blocks happen to be listed in.
+    fn canonical_key(&self) -> Vec<Coord> {
+        let mut coords = self.normalise().0;
+        coords.sort_by_key(|c| (c.x, c.y, c.z));
+        coords
+    }
+}
+
 impl Hash for Orientation {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        // Get the bitmask and feed it into the hasher
-        let dim = Coord::new(4, 4, 4);
-        let placement = Placement::from_orientation(self, &dim);
-        placement.0.hash(state);
+        self.canonical_key().hash(state);
     }
 }
 
 impl PartialEq for Orientation {
     fn eq(&self, other: &Self) -> bool {
-        // Equality based on the bitmask
-        let dim = Coord::new(4, 4, 4);
-        let placement_a = Placement::from_orientation(self, &dim);
-        let placement_b = Placement::from_orientation(other, &dim);
-        placement_a.0 == placement_b.0
+        self.canonical_key() == other.canonical_key()
     }
 }
 
@@ -169,6 +293,15 @@ impl Orientation {
             .for_each(|coord| coord.z = coord.z - min_z);
     }
 
+    /// Number of cells this orientation occupies.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn offset(&self) -> Coord {
         Coord {
             x: self.0.iter().map(|c| c.x).min().unwrap(),
@@ -185,6 +318,12 @@ impl Orientation {
         }
     }
 
+    pub fn reflect(&self) -> Orientation {
+        let mut mirrored = self.clone();
+        mirrored.0.iter_mut().for_each(|coord| coord.x = -coord.x);
+        mirrored.normalise()
+    }
+
     pub fn normalise(&self) -> Orientation {
         let offset = self.offset();
         let blocks = self
@@ -240,6 +379,11 @@ impl Coord {
         }
     }
 
+    /// Total number of cells in a board of this size (`x * y * z`).
+    pub fn cells(&self) -> usize {
+        (self.x * self.y * self.z) as usize
+    }
+
     fn from_str(s: &str) -> Vec<Coord> {
         s.split("-")
             .map(|coord_s| {
@@ -299,45 +443,161 @@ pub struct Puzzle {
     pub pieces: Vec<Piece>,
     pub dim: Coord,
     pub full: Bitset,
+    pub neighbours: NeighbourMasks,
+    /// For each cell index, the `(piece_id, placement_index)` pairs of
+    /// every placement that covers it.
+    pub cell_placements: Vec<Vec<(usize, usize)>>,
 }
 
 impl Puzzle {
+    /// Total number of cells on the board.
+    pub fn num_cells(&self) -> usize {
+        self.dim.cells()
+    }
+
+    /// Board dimensions, read from the CSV header row if it holds three
+    /// numbers (e.g. `5,5,6` for a non-cubic board). Any other header
+    /// (including an ordinary `name,color,coords` column header) is
+    /// ignored, and the caller falls back to a cube sized from the piece
+    /// blocks instead.
+    fn dim_from_header(header: &csv::StringRecord) -> Option<Coord> {
+        let x = header.get(0)?.trim().parse().ok()?;
+        let y = header.get(1)?.trim().parse().ok()?;
+        let z = header.get(2)?.trim().parse().ok()?;
+        Some(Coord::new(x, y, z))
+    }
+
     pub fn from_csv(path: PathBuf) -> io::Result<Self> {
         let file = File::open(path)?;
         let mut rdr = csv::Reader::from_reader(file);
+        let header_dim = Self::dim_from_header(rdr.headers()?);
+
         let mut pieces = vec![];
         for (idx, result) in rdr.records().enumerate() {
             let record = result?;
             let color = record[1].parse().unwrap_or(Color::BrightRed);
+            let allow_reflection = record.get(3).is_some_and(|s| s.parse().unwrap_or(false));
             pieces.push(Piece::new(
                 record[0].color(color).to_string(),
                 format!("{:X}", idx).color(color).to_string(),
                 Orientation(Coord::from_str(&record[2])),
+                allow_reflection,
             ));
         }
 
-        let mut blocks = 0;
-        for piece in &mut pieces {
-            blocks += piece.base.0.len();
+        let blocks: usize = pieces.iter().map(|piece| piece.base.len()).sum();
+        let dim = match header_dim {
+            Some(dim) => dim,
+            None => {
+                // No explicit board size in the header, so fall back to
+                // assuming a cube sized to fit every piece's blocks.
+                let d = (blocks as f64).cbrt().round() as usize;
+                Coord::new(d, d, d)
+            }
+        };
+
+        if blocks != dim.cells() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "pieces cover {} cells but the board ({:?}) has {}",
+                    blocks,
+                    dim,
+                    dim.cells()
+                ),
+            ));
         }
 
-        let d = (blocks as f64).cbrt().round() as usize;
-        let dim = Coord::new(d, d, d);
-
         for piece in &mut pieces {
             piece.placements = Self::piece_placements(piece, &dim);
         }
 
         let full = Self::full(&dim);
+        let neighbours = NeighbourMasks::build(&dim);
+        let cell_placements = Self::cell_placements_index(&pieces, &dim);
 
         Ok(Puzzle {
             name: "Bedlam Cube".to_string(),
             pieces,
             dim,
             full,
+            neighbours,
+            cell_placements,
         })
     }
 
+    /// Invert `piece.placements` into a per-cell lookup of which
+    /// `(piece_id, placement_index)` pairs cover that cell.
+    fn cell_placements_index(pieces: &[Piece], dim: &Coord) -> Vec<Vec<(usize, usize)>> {
+        let cells = dim.cells();
+        let mut index = vec![vec![]; cells];
+        for (pid, piece) in pieces.iter().enumerate() {
+            for (pidx, placement) in piece.placements().iter().enumerate() {
+                // Walk only the set bits of the placement, rather than
+                // every cell on the board, since placements are sparse.
+                for (word_idx, &word) in placement.0.iter().enumerate() {
+                    let mut bits = word;
+                    while bits != 0 {
+                        let cell = word_idx * WORD_BITS + bits.trailing_zeros() as usize;
+                        index[cell].push((pid, pidx));
+                        bits &= bits - 1;
+                    }
+                }
+            }
+        }
+        index
+    }
+
+    fn cell_index(coord: &Coord, dim: &Coord) -> usize {
+        (coord.z * dim.y * dim.x + coord.y * dim.x + coord.x) as usize
+    }
+
+    /// The board's rotational symmetry group: every one of the 24 cube
+    /// rotations (reusing `Piece`'s orientation machinery) that maps the
+    /// board onto itself, expressed as the cell-index permutation it
+    /// induces.
+    pub fn symmetries(&self) -> Vec<Vec<usize>> {
+        let cells = self.dim.cells();
+        let board: Vec<Coord> = (0..self.dim.x)
+            .flat_map(|x| (0..self.dim.y).flat_map(move |y| (0..self.dim.z).map(move |z| Coord { x, y, z })))
+            .collect();
+        let board = Orientation(board);
+
+        Piece::rotations(&board)
+            .iter()
+            .filter(|rotated| rotated.bounds() == board.bounds())
+            .filter_map(|rotated| {
+                let mask = Bitset::from_orientation(rotated, &self.dim);
+                if mask != self.full {
+                    return None;
+                }
+
+                let mut perm = vec![0; cells];
+                for (from, to) in board.0.iter().zip(rotated.0.iter()) {
+                    perm[Self::cell_index(from, &self.dim)] = Self::cell_index(to, &self.dim);
+                }
+                Some(perm)
+            })
+            .collect()
+    }
+
+    /// Build a board-sized mask of every cell satisfying `predicate(x, y, z)`.
+    fn mask_where(dim: &Coord, predicate: impl Fn(i64, i64, i64) -> bool) -> Bitset {
+        let cells = dim.cells();
+        let mut mask = Bitset::empty(Bitset::words_for(cells));
+        for x in 0..dim.x {
+            for y in 0..dim.y {
+                for z in 0..dim.z {
+                    if predicate(x, y, z) {
+                        let index = z * dim.y * dim.x + y * dim.x + x;
+                        mask.set(index as usize);
+                    }
+                }
+            }
+        }
+        mask
+    }
+
     pub fn show(&self, arrangement: &Arrangement) {
         for y in (0..self.dim.y).rev() {
             for z in 0..self.dim.z {
@@ -445,7 +705,8 @@ impl Puzzle {
     }
 
     pub fn full(dim: &Coord) -> Bitset {
-        let mut full = Bitset::empty();
+        let cells = dim.cells();
+        let mut full = Bitset::empty(Bitset::words_for(cells));
         for x in 0..dim.x {
             for y in 0..dim.y {
                 for z in 0..dim.z {
@@ -465,25 +726,113 @@ pub struct Arrangement {
 }
 
 impl Arrangement {
-    pub fn new() -> Arrangement {
+    pub fn new(words: usize) -> Arrangement {
         Arrangement {
-            occupied: Bitset::empty(),
+            occupied: Bitset::empty(words),
             placements: vec![],
         }
     }
 
     pub fn push(&mut self, piece: usize, placement: Bitset) {
-        self.occupied = self.occupied.union(placement);
+        self.occupied = self.occupied.union(&placement);
         self.placements.push((piece, placement));
     }
 
     pub fn pop(&mut self) -> Option<(usize, Bitset)> {
         match self.placements.pop() {
             Some((piece, placement)) => {
-                self.occupied = self.occupied.xor(placement);
+                self.occupied = self.occupied.xor(&placement);
                 Some((piece, placement))
             }
             None => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shl_carries_a_bit_across_a_word_boundary() {
+        let mut bits = Bitset::empty(2);
+        bits.set(WORD_BITS - 1);
+
+        let shifted = bits.shl(2);
+
+        assert!(!shifted.get(WORD_BITS - 1));
+        assert!(shifted.get(WORD_BITS + 1));
+        assert_eq!(shifted.count_ones(), 1);
+    }
+
+    #[test]
+    fn shl_discards_bits_that_fall_off_the_top() {
+        let mut bits = Bitset::empty(1);
+        bits.set(WORD_BITS - 1);
+
+        let shifted = bits.shl(1);
+
+        assert!(shifted.is_empty());
+    }
+
+    #[test]
+    fn shr_carries_a_bit_across_a_word_boundary() {
+        let mut bits = Bitset::empty(2);
+        bits.set(WORD_BITS + 1);
+
+        let shifted = bits.shr(2);
+
+        assert!(!shifted.get(WORD_BITS + 1));
+        assert!(shifted.get(WORD_BITS - 1));
+        assert_eq!(shifted.count_ones(), 1);
+    }
+
+    #[test]
+    fn shr_discards_bits_that_fall_off_the_bottom() {
+        let mut bits = Bitset::empty(1);
+        bits.set(0);
+
+        let shifted = bits.shr(1);
+
+        assert!(shifted.is_empty());
+    }
+
+    #[test]
+    fn neighbour_masks_do_not_wrap_across_board_edges() {
+        // 2x2x2 board, cell index = z*4 + y*2 + x.
+        let dim = Coord::new(2, 2, 2);
+        let neighbours = NeighbourMasks::build(&dim);
+
+        let mut corner = Bitset::empty(Bitset::words_for(dim.cells()));
+        corner.set(0); // (0, 0, 0)
+
+        let expanded = neighbours.expand(&corner);
+
+        // Face-adjacent cells of (0,0,0) are (1,0,0), (0,1,0) and (0,0,1);
+        // none of them should wrap to the opposite edge of the board.
+        assert!(expanded.get(Puzzle::cell_index(&Coord::new(1, 0, 0), &dim)));
+        assert!(expanded.get(Puzzle::cell_index(&Coord::new(0, 1, 0), &dim)));
+        assert!(expanded.get(Puzzle::cell_index(&Coord::new(0, 0, 1), &dim)));
+        assert_eq!(expanded.count_ones(), 3);
+    }
+
+    #[test]
+    fn allow_reflection_adds_mirror_image_orientations() {
+        // A screw-shaped tetracube (one step along each of x, y and z):
+        // chiral, so no rotation can reach its mirror image.
+        let base = Orientation(vec![
+            Coord::new(0, 0, 0),
+            Coord::new(1, 0, 0),
+            Coord::new(1, 1, 0),
+            Coord::new(1, 1, 1),
+        ]);
+        let mirrored = base.reflect();
+        assert_ne!(base, mirrored);
+
+        let rigid = Piece::new("S".to_string(), "S".to_string(), base.clone(), false);
+        let chiral = Piece::new("S".to_string(), "S".to_string(), base, true);
+
+        assert!(!rigid.orientations().contains(&mirrored));
+        assert!(chiral.orientations().contains(&mirrored));
+    }
+}
@@ -4,11 +4,32 @@ use crate::puzzle::{Arrangement, Bitset, Board, Coord, Orientation, Placement, P
 
 use std::time::Instant;
 
+/// Whether some subset of `sizes` sums to exactly `target`.
+fn sizes_can_sum_to(sizes: &[usize], target: usize) -> bool {
+    let mut reachable = vec![false; target + 1];
+    reachable[0] = true;
+    for &size in sizes {
+        for sum in (size..=target).rev() {
+            if reachable[sum - size] {
+                reachable[sum] = true;
+            }
+        }
+    }
+    reachable[target]
+}
+
 pub struct Solver {
     pub explored: usize,
     pub solutions: Vec<Vec<(usize, Placement)>>,
     pub start_time: Option<Instant>,
     pub verbose: bool,
+    /// Symmetries of the board that also fix the current seed placement.
+    /// `add_solution` uses these to discard solutions that are just a
+    /// stabilizer-image of one already found, so the reported count is
+    /// reduced by the full symmetry group rather than just by the seed's
+    /// orbit.
+    stabilizer: Vec<Vec<usize>>,
+    seen_solutions: std::collections::HashSet<Vec<(usize, Placement)>>,
 }
 
 impl Solver {
@@ -18,10 +39,24 @@ impl Solver {
             solutions: Vec::new(),
             start_time: None,
             verbose,
+            stabilizer: vec![],
+            seen_solutions: std::collections::HashSet::new(),
         }
     }
 
     fn add_solution(&mut self, puzzle: &Puzzle, arrangement: Arrangement) {
+        // Each piece appears once per solution, so ordering by piece id
+        // gives a key that doesn't depend on the order pieces were placed.
+        let mut key = arrangement.placements.clone();
+        key.sort_by_key(|(pid, _)| *pid);
+        if self.seen_solutions.contains(&key) {
+            return;
+        }
+        for perm in &self.stabilizer {
+            let image = Self::apply_permutation_to_solution(&key, perm);
+            self.seen_solutions.insert(image);
+        }
+
         self.solutions.push(arrangement.placements.clone());
 
         if self.verbose {
@@ -38,13 +73,13 @@ impl Solver {
         }
     }
 
-    pub fn has_full_coverage(&self, puzzle: &Puzzle, tmp: Bitset, pieces: &Vec<usize>) -> bool {
+    pub fn has_full_coverage(&self, puzzle: &Puzzle, tmp: &Bitset, pieces: &Vec<usize>) -> bool {
         let mut coverage = tmp.clone();
         for pid in pieces {
             let piece = &puzzle.pieces[*pid];
             for placement in piece.placements() {
-                if !tmp.intersects(*placement) {
-                    coverage = coverage.union(*placement);
+                if !tmp.intersects(placement) {
+                    coverage = coverage.union(placement);
                     if coverage == puzzle.full {
                         return true;
                     }
@@ -54,12 +89,12 @@ impl Solver {
         coverage == puzzle.full
     }
 
-    pub fn can_pieces_fit(&self, puzzle: &Puzzle, tmp: Bitset, pieces: &Vec<usize>) -> bool {
+    pub fn can_pieces_fit(&self, puzzle: &Puzzle, tmp: &Bitset, pieces: &Vec<usize>) -> bool {
         for pid in pieces {
             if puzzle.pieces[*pid]
                 .placements
                 .iter()
-                .all(|placement: &Placement| !tmp.intersects(*placement))
+                .all(|placement: &Placement| !tmp.intersects(placement))
             {
                 return false;
             }
@@ -67,23 +102,59 @@ impl Solver {
         return true;
     }
 
-    fn new_cube(
-        &self,
-        puzzle: &Puzzle,
-        arrangement: &Arrangement,
-        prev: usize,
-    ) -> Option<(usize, Bitset)> {
+    /// Reject boards that leave a pocket of empty cells no remaining piece
+    /// could possibly fill, by flood-filling each disconnected free region
+    /// and checking its size against the pieces left.
+    pub fn has_no_dead_pockets(&self, puzzle: &Puzzle, board: &Bitset, pieces: &[usize]) -> bool {
+        let mut free = puzzle.full.xor(board);
+        if free.is_empty() {
+            return true;
+        }
+        if pieces.is_empty() {
+            return false;
+        }
+
+        let sizes: Vec<usize> = pieces
+            .iter()
+            .map(|&pid| puzzle.pieces[pid].base.len())
+            .collect();
+        let min_size = *sizes.iter().min().unwrap();
+
+        while let Some(seed) = free.first_set() {
+            let mut region = Bitset::empty(free.0.len());
+            region.set(seed);
+            loop {
+                let grown = region.union(&puzzle.neighbours.expand(&region).intersection(&free));
+                if grown == region {
+                    break;
+                }
+                region = grown;
+            }
+
+            let region_size = region.count_ones();
+            if region_size < min_size || !sizes_can_sum_to(&sizes, region_size) {
+                return false;
+            }
+
+            free = free.xor(&region);
+        }
+        true
+    }
+
+    fn new_cube(&self, puzzle: &Puzzle, arrangement: &Arrangement, prev: usize) -> Option<usize> {
         let mut cube = prev;
-        let mut mask = 1 << cube;
 
-        while mask & arrangement.occupied.0 != 0 {
+        while cube < puzzle.num_cells() && arrangement.occupied.get(cube) {
             cube += 1;
-            mask <<= 1;
         }
 
-        // do a check to ensure not isolated cube
+        // Isolated-cube pruning now happens via `has_no_dead_pockets`.
+
+        if cube >= puzzle.num_cells() {
+            return None;
+        }
 
-        Some((cube, Bitset(mask)))
+        Some(cube)
     }
 
     fn solve_board(
@@ -100,61 +171,180 @@ impl Solver {
             return;
         }
 
-        let (cube, mask) = match self.new_cube(puzzle, arrangement, prev) {
-            Some((c, m)) => (c, m),
+        let cube = match self.new_cube(puzzle, arrangement, prev) {
+            Some(c) => c,
             None => return,
         };
 
-        for (idx, pid) in remaining.iter().enumerate() {
+        // Only placements that actually cover `cube` and belong to a piece
+        // we haven't placed yet are worth trying.
+        for &(pid, pidx) in &puzzle.cell_placements[cube] {
+            let idx = match remaining.iter().position(|&p| p == pid) {
+                Some(idx) => idx,
+                None => continue,
+            };
+            let placement = &puzzle.pieces[pid].placements()[pidx];
+            let new_board = arrangement.occupied.union(placement);
+
             let mut other_pieces = remaining.clone();
             other_pieces.remove(idx);
-            let piece = &puzzle.pieces[*pid];
 
-            for &placement in piece.placements() {
-                let new_board = arrangement.occupied.union(placement);
-                if !arrangement.occupied.intersects(placement)
-                    && placement.intersects(mask)
-                    && self.has_full_coverage(puzzle, new_board, &other_pieces)
-                    && self.can_pieces_fit(puzzle, new_board, &other_pieces)
-                {
-                    arrangement.push(*pid, placement);
-                    self.solve_board(puzzle, arrangement, cube, &other_pieces);
-                    arrangement.pop();
-                }
+            if !arrangement.occupied.intersects(placement)
+                && self.has_no_dead_pockets(puzzle, &new_board, &other_pieces)
+                && self.has_full_coverage(puzzle, &new_board, &other_pieces)
+                && self.can_pieces_fit(puzzle, &new_board, &other_pieces)
+            {
+                arrangement.push(pid, placement.clone());
+                self.solve_board(puzzle, arrangement, cube, &other_pieces);
+                arrangement.pop();
             }
         }
     }
 
     pub fn begin(&mut self, puzzle: &Puzzle) {
         self.start_time = Some(Instant::now());
-        let mut arrangement = Arrangement::new();
+        let mut arrangement = Arrangement::new(puzzle.full.0.len());
 
-        let (cid, contrained) = puzzle
+        let (cid, constrained) = puzzle
             .pieces
             .iter()
             .enumerate()
             .min_by_key(|(_, p)| p.placements.len())
             .unwrap();
 
-        // println!("Constrained piece: {:?}", contrained);
-
-        // for (idx, rot) in puzzle.rotate_within(&contrained.base).iter().enumerate() {
-        //     println!("Rot: {}", idx);
-        //     let bits = Bitset::from_orientation(rot);
-        //     puzzle.show_bit(&bits);
-        // }
-
-        let placements = vec![Bitset(0x0000000000000272), Bitset(0x0000000002720000)];
+        // Restrict the most-constrained piece's first placement to one
+        // representative per orbit of the board's own symmetry group, so
+        // we get exactly one solution per equivalence class instead of
+        // hard-coding seeds for a specific puzzle.
+        let symmetries = puzzle.symmetries();
+        let seeds = Self::orbit_representatives(&constrained.placements, &symmetries);
 
         let remaining = (0..puzzle.pieces.len()).filter(|&x| x != cid).collect();
 
-        for placement in placements {
+        for placement in seeds {
+            // A seed placement can have its own non-trivial stabilizer
+            // (symmetries that map it onto itself), so solutions built on
+            // top of it still come in stabilizer-sized groups; `add_solution`
+            // uses this to collapse each such group to one representative.
+            self.stabilizer = Self::stabilizer_of(&placement, &symmetries);
+            self.seen_solutions.clear();
+
             arrangement.push(cid, placement);
             self.solve_board(puzzle, &mut arrangement, 0, &remaining);
             arrangement.pop();
         }
+    }
+
+    fn apply_permutation(placement: &Placement, perm: &[usize]) -> Placement {
+        let mut out = Bitset::empty(placement.0.len());
+        for (cell, &target) in perm.iter().enumerate() {
+            if placement.get(cell) {
+                out.set(target);
+            }
+        }
+        out
+    }
+
+    fn apply_permutation_to_solution(
+        solution: &[(usize, Placement)],
+        perm: &[usize],
+    ) -> Vec<(usize, Placement)> {
+        solution
+            .iter()
+            .map(|(pid, placement)| (*pid, Self::apply_permutation(placement, perm)))
+            .collect()
+    }
+
+    /// The subgroup of `symmetries` that maps `placement` onto itself.
+    fn stabilizer_of(placement: &Placement, symmetries: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        symmetries
+            .iter()
+            .filter(|perm| Self::apply_permutation(placement, perm) == *placement)
+            .cloned()
+            .collect()
+    }
+
+    /// One placement per orbit of `placements` under the symmetry group
+    /// `symmetries`, i.e. with every placement reachable from another by a
+    /// board symmetry discarded.
+    fn orbit_representatives(placements: &[Placement], symmetries: &[Vec<usize>]) -> Vec<Placement> {
+        let mut seen = std::collections::HashSet::new();
+        let mut representatives = vec![];
+
+        for placement in placements {
+            if seen.contains(placement) {
+                continue;
+            }
+            for perm in symmetries {
+                seen.insert(Self::apply_permutation(placement, perm));
+            }
+            representatives.push(placement.clone());
+        }
+
+        representatives
+    }
+
+    /// Alternative backend: solve via exact cover using Dancing Links
+    /// instead of the bespoke backtracker in `solve_board`.
+    pub fn begin_dlx(&mut self, puzzle: &Puzzle) {
+        self.start_time = Some(Instant::now());
+
+        let (solutions, explored) = crate::dlx::solve(puzzle);
+        self.explored = explored;
+
+        for placements in solutions {
+            let mut arrangement = Arrangement::new(puzzle.full.0.len());
+            for (pid, placement) in placements {
+                arrangement.push(pid, placement);
+            }
+            self.add_solution(puzzle, arrangement);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Eight unit-cube pieces packed into a 2x2x2 board: every piece has
+    /// exactly one orientation and one placement per cell, so the raw
+    /// (unreduced) solution count is `8!` and the board's rotation group
+    /// has the full cube order of 24, giving a symmetry-reduced count of
+    /// `8! / 24`.
+    fn unit_cube_puzzle() -> Puzzle {
+        let csv = "2,2,2\n\
+                   P0,black,000\n\
+                   P1,red,000\n\
+                   P2,green,000\n\
+                   P3,yellow,000\n\
+                   P4,blue,000\n\
+                   P5,magenta,000\n\
+                   P6,cyan,000\n\
+                   P7,white,000\n";
+
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("polycube-test-{}.csv", std::process::id()));
+        std::fs::write(&path, csv).unwrap();
+        let puzzle = Puzzle::from_csv(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        puzzle
+    }
+
+    #[test]
+    fn symmetry_reduced_count_matches_raw_count_over_symmetry_order() {
+        let puzzle = unit_cube_puzzle();
+
+        let mut dlx_solver = Solver::build(false);
+        dlx_solver.begin_dlx(&puzzle);
+        let raw = dlx_solver.solutions.len();
+        assert_eq!(raw, 40320);
+
+        let symmetry_order = puzzle.symmetries().len();
+        assert_eq!(symmetry_order, 24);
 
-        // let remaining = (0..puzzle.pieces.len()).collect();
-        // self.solve_board(puzzle, &mut arrangement, 0, &remaining);
+        let mut solver = Solver::build(false);
+        solver.begin(&puzzle);
+        assert_eq!(solver.solutions.len(), raw / symmetry_order);
     }
 }